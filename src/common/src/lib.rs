@@ -0,0 +1,2 @@
+pub mod federation;
+pub mod utils;
@@ -1,15 +1,20 @@
 use crate::constants::{PEER_IP_HEADER_NAME, PROXY_MODE, TRUSTED_PROXIES};
-use actix_web::dev::ServiceRequest;
-use actix_web::http::header::HeaderMap;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
 use actix_web::HttpRequest;
 use base64::{engine, engine::general_purpose, Engine as _};
+use futures_util::future::LocalBoxFuture;
 use gethostname::gethostname;
+use once_cell::sync::Lazy;
 use rand::distributions::Alphanumeric;
 use rand::Rng;
 use rauthy_error::{ErrorResponse, ErrorResponseType};
 use std::env;
+use std::future::{ready, Ready};
 use std::net::IpAddr;
+use std::rc::Rc;
 use std::str::FromStr;
+use std::time::Duration;
 use tracing::{error, trace};
 
 const B64_URL_SAFE: engine::GeneralPurpose = general_purpose::URL_SAFE;
@@ -155,8 +160,12 @@ pub fn real_ip_from_req(req: &HttpRequest) -> Result<IpAddr, ErrorResponse> {
         check_trusted_proxy(&peer_ip)?;
         Ok(ip)
     } else if *PROXY_MODE {
-        check_trusted_proxy(&peer_ip)?;
-        parse_peer_addr(req.connection_info().realip_remote_addr())
+        if let Some(ip) = real_ip_from_forwarded_for(req.headers(), &peer_ip)? {
+            Ok(ip)
+        } else {
+            check_trusted_proxy(&peer_ip)?;
+            parse_peer_addr(req.connection_info().realip_remote_addr())
+        }
     } else {
         Ok(peer_ip)
     }
@@ -169,8 +178,12 @@ pub fn real_ip_from_svc_req(req: &ServiceRequest) -> Result<IpAddr, ErrorRespons
         check_trusted_proxy(&peer_ip)?;
         Ok(ip)
     } else if *PROXY_MODE {
-        check_trusted_proxy(&peer_ip)?;
-        parse_peer_addr(req.connection_info().realip_remote_addr())
+        if let Some(ip) = real_ip_from_forwarded_for(req.headers(), &peer_ip)? {
+            Ok(ip)
+        } else {
+            check_trusted_proxy(&peer_ip)?;
+            parse_peer_addr(req.connection_info().realip_remote_addr())
+        }
     } else {
         Ok(peer_ip)
     }
@@ -252,6 +265,322 @@ fn ip_from_cust_header(headers: &HeaderMap) -> Option<IpAddr> {
     None
 }
 
+const X_FORWARDED_FOR: &str = "X-Forwarded-For";
+
+// Parses a (possibly multi-hop) `X-Forwarded-For` header and walks it from right to left, since
+// each hop appends its own address and the right-most entry is therefore the closest proxy.
+// Returns `None` if the header is missing, empty or does not contain a single parsable entry.
+//
+// Only called once an operator has opted into `*PROXY_MODE` - a direct, non-proxied client
+// happening to send this header must never affect or reject its own request. Even then, the
+// directly connected `peer_ip` must be a trusted proxy itself, or the header is never consulted
+// at all. If every single entry turns out to be a trusted proxy too, the left-most entry is
+// returned as a best-effort fallback.
+#[inline(always)]
+fn real_ip_from_forwarded_for(
+    headers: &HeaderMap,
+    peer_ip: &IpAddr,
+) -> Result<Option<IpAddr>, ErrorResponse> {
+    let Some(ips) = ips_from_forwarded_for_header(headers) else {
+        return Ok(None);
+    };
+
+    check_trusted_proxy(peer_ip)?;
+
+    for ip in ips.iter().rev() {
+        if check_trusted_proxy(ip).is_err() {
+            return Ok(Some(*ip));
+        }
+    }
+
+    // every hop was a trusted proxy -> fall back to the left-most (original client) entry
+    Ok(ips.into_iter().next())
+}
+
+#[inline(always)]
+fn ips_from_forwarded_for_header(headers: &HeaderMap) -> Option<Vec<IpAddr>> {
+    let value = headers.get(X_FORWARDED_FOR)?.to_str().ok()?;
+
+    let ips = value
+        .split(',')
+        .filter_map(|entry| IpAddr::from_str(entry.trim()).ok())
+        .collect::<Vec<_>>();
+    if ips.is_empty() {
+        None
+    } else {
+        Some(ips)
+    }
+}
+
+// Default values for the security response headers below, used whenever the operator does not
+// override them via env config.
+const DEFAULT_SEC_HEADER_X_FRAME_OPTIONS: &str = "DENY";
+const DEFAULT_SEC_HEADER_CONTENT_SECURITY_POLICY: &str = "default-src 'self'";
+const DEFAULT_SEC_HEADER_REFERRER_POLICY: &str = "no-referrer";
+const DEFAULT_SEC_HEADER_PERMISSIONS_POLICY: &str =
+    "accelerometer=(), geolocation=(), microphone=()";
+
+// Same env-backed construction pattern as `build_trusted_proxies`: each header value can be
+// overridden via its own env var, falling back to a safe default when unset.
+pub static SEC_HEADER_X_FRAME_OPTIONS: Lazy<String> =
+    Lazy::new(|| build_sec_header("SEC_HEADER_X_FRAME_OPTIONS", DEFAULT_SEC_HEADER_X_FRAME_OPTIONS));
+pub static SEC_HEADER_CONTENT_SECURITY_POLICY: Lazy<String> = Lazy::new(|| {
+    build_sec_header(
+        "SEC_HEADER_CONTENT_SECURITY_POLICY",
+        DEFAULT_SEC_HEADER_CONTENT_SECURITY_POLICY,
+    )
+});
+pub static SEC_HEADER_REFERRER_POLICY: Lazy<String> =
+    Lazy::new(|| build_sec_header("SEC_HEADER_REFERRER_POLICY", DEFAULT_SEC_HEADER_REFERRER_POLICY));
+pub static SEC_HEADER_PERMISSIONS_POLICY: Lazy<String> = Lazy::new(|| {
+    build_sec_header(
+        "SEC_HEADER_PERMISSIONS_POLICY",
+        DEFAULT_SEC_HEADER_PERMISSIONS_POLICY,
+    )
+});
+
+fn build_sec_header(env_var: &str, default: &str) -> String {
+    env::var(env_var).unwrap_or_else(|_| default.to_string())
+}
+
+// Actix middleware that injects hardening response headers (X-Frame-Options,
+// X-Content-Type-Options, Content-Security-Policy, Referrer-Policy and Permissions-Policy) on
+// every response.
+//
+// WebSocket upgrade requests are detected via `Connection: upgrade` + `Upgrade: websocket` on
+// the request and are passed through without the frame-blocking headers, since those can break
+// WebSocket connections through some reverse proxies.
+pub struct SecurityHeaders;
+
+impl<S, B> Transform<S, ServiceRequest> for SecurityHeaders
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = SecurityHeadersMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SecurityHeadersMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct SecurityHeadersMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for SecurityHeadersMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let is_ws_upgrade = is_websocket_upgrade(req.headers());
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+            if is_ws_upgrade {
+                return Ok(res);
+            }
+
+            let headers = res.headers_mut();
+            insert_header(headers, "x-frame-options", &SEC_HEADER_X_FRAME_OPTIONS);
+            insert_header(headers, "x-content-type-options", "nosniff");
+            insert_header(
+                headers,
+                "content-security-policy",
+                &SEC_HEADER_CONTENT_SECURITY_POLICY,
+            );
+            insert_header(headers, "referrer-policy", &SEC_HEADER_REFERRER_POLICY);
+            insert_header(
+                headers,
+                "permissions-policy",
+                &SEC_HEADER_PERMISSIONS_POLICY,
+            );
+
+            Ok(res)
+        })
+    }
+}
+
+#[inline(always)]
+fn is_websocket_upgrade(headers: &HeaderMap) -> bool {
+    let has_upgrade_conn = headers
+        .get(actix_web::http::header::CONNECTION)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.to_ascii_lowercase().contains("upgrade"));
+    let is_ws = headers
+        .get(actix_web::http::header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"));
+
+    has_upgrade_conn && is_ws
+}
+
+#[inline(always)]
+fn insert_header(headers: &mut HeaderMap, name: &'static str, value: &str) {
+    if value.is_empty() {
+        return;
+    }
+    match (HeaderName::from_static(name), HeaderValue::from_str(value)) {
+        (name, Ok(value)) => {
+            headers.insert(name, value);
+        }
+        (_, Err(err)) => {
+            error!("Cannot build security response header '{}': {}", name, err);
+        }
+    }
+}
+
+// The single outbound HTTP client every subsystem should use for server-initiated requests
+// (upstream JWKS / discovery fetches, webhooks, event notifications, ...), so proxy
+// configuration is only ever set up once.
+pub static HTTP_CLIENT: Lazy<reqwest::Client> =
+    Lazy::new(|| build_http_client().expect("Error building the shared outbound HTTP client"));
+
+enum NoProxyEntry {
+    Cidr(cidr::IpCidr),
+    Host(String),
+}
+
+// Sane defaults so a hung or slow upstream cannot block the shared client's callers
+// indefinitely - this client is now used by every subsystem that makes outbound calls.
+const OUTBOUND_HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+const OUTBOUND_HTTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Builds the shared outbound `reqwest::Client`, routing traffic through an `OUTBOUND_PROXY_URL`
+// if one is configured. The scheme of that URL decides the proxy protocol: `http(s)://` dials
+// an HTTP CONNECT proxy, `socks5://` a SOCKS5 proxy. `OUTBOUND_PROXY_USERNAME` /
+// `OUTBOUND_PROXY_PASSWORD` add proxy auth, and `OUTBOUND_PROXY_NO_PROXY` is a bypass list of
+// CIDRs and/or hostnames (reusing the `cidr` crate already used by `build_trusted_proxies`) for
+// destinations that must always be reached directly, e.g. for egress-restricted or Tor-style
+// deployments.
+fn build_http_client() -> Result<reqwest::Client, ErrorResponse> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(OUTBOUND_HTTP_TIMEOUT)
+        .connect_timeout(OUTBOUND_HTTP_CONNECT_TIMEOUT);
+
+    if let Some(proxy) = build_outbound_proxy()? {
+        builder = builder.proxy(proxy);
+    } else {
+        builder = builder.no_proxy();
+    }
+
+    builder.build().map_err(|err| {
+        error!("Error building outbound HTTP client: {}", err);
+        ErrorResponse::new(
+            ErrorResponseType::Internal,
+            "Cannot build outbound HTTP client",
+        )
+    })
+}
+
+fn build_outbound_proxy() -> Result<Option<reqwest::Proxy>, ErrorResponse> {
+    let raw_proxy_url = match env::var("OUTBOUND_PROXY_URL") {
+        Ok(url) if !url.trim().is_empty() => url,
+        _ => return Ok(None),
+    };
+    let username = env::var("OUTBOUND_PROXY_USERNAME").ok();
+    let password = env::var("OUTBOUND_PROXY_PASSWORD").unwrap_or_default();
+
+    let parsed_proxy_url = proxy_url_with_auth(&raw_proxy_url, username.as_deref(), &password)?;
+    let is_socks5 = matches!(parsed_proxy_url.scheme(), "socks5" | "socks5h");
+
+    let proxy_url = parsed_proxy_url.to_string();
+    let no_proxy = build_no_proxy_list();
+    let mut proxy = reqwest::Proxy::custom(move |url| {
+        let host = url.host_str().unwrap_or_default();
+        if is_no_proxy_host(host, &no_proxy) {
+            None
+        } else {
+            reqwest::Url::parse(&proxy_url).ok()
+        }
+    });
+
+    // SOCKS5 has no HTTP CONNECT handshake for `Proxy::basic_auth` to attach to; those
+    // credentials were already embedded into the URL's userinfo above. HTTP(S) CONNECT proxies
+    // use this instead.
+    if !is_socks5 {
+        if let Some(username) = &username {
+            proxy = proxy.basic_auth(username, &password);
+        }
+    }
+
+    Ok(Some(proxy))
+}
+
+// Parses `raw_url` and, for a `socks5(h)://` proxy, embeds `username`/`password` into its
+// userinfo - the only place a SOCKS5 client looks for proxy credentials.
+fn proxy_url_with_auth(
+    raw_url: &str,
+    username: Option<&str>,
+    password: &str,
+) -> Result<reqwest::Url, ErrorResponse> {
+    let mut url = reqwest::Url::parse(raw_url).map_err(|err| {
+        error!("Cannot parse OUTBOUND_PROXY_URL '{}': {}", raw_url, err);
+        ErrorResponse::new(ErrorResponseType::Internal, "Invalid OUTBOUND_PROXY_URL")
+    })?;
+
+    if matches!(url.scheme(), "socks5" | "socks5h") {
+        if let Some(username) = username {
+            url.set_username(username).map_err(|_| {
+                ErrorResponse::new(ErrorResponseType::Internal, "Invalid OUTBOUND_PROXY_URL")
+            })?;
+            url.set_password(Some(password)).map_err(|_| {
+                ErrorResponse::new(ErrorResponseType::Internal, "Invalid OUTBOUND_PROXY_URL")
+            })?;
+        }
+    }
+
+    Ok(url)
+}
+
+fn build_no_proxy_list() -> Vec<NoProxyEntry> {
+    let raw = env::var("OUTBOUND_PROXY_NO_PROXY").unwrap_or_default();
+    let mut entries = Vec::new();
+
+    for line in raw.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        match cidr::IpCidr::from_str(trimmed) {
+            Ok(cidr) => entries.push(NoProxyEntry::Cidr(cidr)),
+            Err(_) => entries.push(NoProxyEntry::Host(trimmed.to_lowercase())),
+        }
+    }
+
+    entries
+}
+
+#[inline(always)]
+fn is_no_proxy_host(host: &str, entries: &[NoProxyEntry]) -> bool {
+    if host.is_empty() {
+        return false;
+    }
+    let ip = IpAddr::from_str(host).ok();
+    let host_lower = host.to_lowercase();
+
+    entries.iter().any(|entry| match entry {
+        NoProxyEntry::Cidr(cidr) => ip.is_some_and(|ip| cidr.contains(&ip)),
+        NoProxyEntry::Host(bypassed) => {
+            &host_lower == bypassed || host_lower.ends_with(&format!(".{bypassed}"))
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -316,4 +645,144 @@ mod tests {
         assert!(check_trusted_proxy(&IpAddr::from_str("10.10.10.9").unwrap()).is_err());
         assert!(check_trusted_proxy(&IpAddr::from_str("10.10.10.12").unwrap()).is_err());
     }
+
+    fn headers_with_xff(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            actix_web::http::header::HeaderName::from_static("x-forwarded-for"),
+            actix_web::http::header::HeaderValue::from_str(value).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_real_ip_from_forwarded_for() {
+        env::set_var("TRUSTED_PROXIES", "10.0.0.0/24");
+
+        let peer_ip = IpAddr::from_str("10.0.0.2").unwrap();
+
+        // multi-hop chain -> right-most non-trusted entry is the real client
+        let headers = headers_with_xff("1.1.1.1, 10.0.0.3, 10.0.0.2");
+        assert_eq!(
+            real_ip_from_forwarded_for(&headers, &peer_ip).unwrap(),
+            Some(IpAddr::from_str("10.0.0.3").unwrap())
+        );
+
+        // malformed entries are skipped rather than erroring the whole request
+        let headers = headers_with_xff("not-an-ip, 10.0.0.3");
+        assert_eq!(
+            real_ip_from_forwarded_for(&headers, &peer_ip).unwrap(),
+            Some(IpAddr::from_str("10.0.0.3").unwrap())
+        );
+
+        // every entry is a trusted proxy -> fall back to the left-most one
+        let headers = headers_with_xff("10.0.0.5, 10.0.0.4, 10.0.0.3");
+        assert_eq!(
+            real_ip_from_forwarded_for(&headers, &peer_ip).unwrap(),
+            Some(IpAddr::from_str("10.0.0.5").unwrap())
+        );
+
+        // no header present -> None, caller falls through to the next strategy
+        let headers = HeaderMap::new();
+        assert_eq!(
+            real_ip_from_forwarded_for(&headers, &peer_ip).unwrap(),
+            None
+        );
+
+        // peer itself is not a trusted proxy -> header must not be consulted at all
+        let untrusted_peer = IpAddr::from_str("1.2.3.4").unwrap();
+        let headers = headers_with_xff("5.5.5.5, 6.6.6.6");
+        assert!(real_ip_from_forwarded_for(&headers, &untrusted_peer).is_err());
+    }
+
+    #[test]
+    fn test_is_websocket_upgrade() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_websocket_upgrade(&headers));
+
+        headers.insert(
+            actix_web::http::header::CONNECTION,
+            HeaderValue::from_static("Upgrade"),
+        );
+        headers.insert(
+            actix_web::http::header::UPGRADE,
+            HeaderValue::from_static("websocket"),
+        );
+        assert!(is_websocket_upgrade(&headers));
+
+        headers.insert(
+            actix_web::http::header::UPGRADE,
+            HeaderValue::from_static("h2c"),
+        );
+        assert!(!is_websocket_upgrade(&headers));
+    }
+
+    #[test]
+    fn test_outbound_proxy_no_proxy_list() {
+        env::set_var(
+            "OUTBOUND_PROXY_NO_PROXY",
+            "10.0.0.0/24\ninternal.example.com",
+        );
+        let entries = build_no_proxy_list();
+
+        assert!(is_no_proxy_host("10.0.0.42", &entries));
+        assert!(!is_no_proxy_host("10.0.1.1", &entries));
+
+        assert!(is_no_proxy_host("internal.example.com", &entries));
+        assert!(is_no_proxy_host("svc.internal.example.com", &entries));
+        assert!(!is_no_proxy_host("external.example.com", &entries));
+
+        assert!(!is_no_proxy_host("", &entries));
+    }
+
+    #[test]
+    fn test_build_sec_header() {
+        env::remove_var("SEC_HEADER_X_FRAME_OPTIONS");
+        assert_eq!(
+            build_sec_header("SEC_HEADER_X_FRAME_OPTIONS", "DENY"),
+            "DENY"
+        );
+
+        env::set_var("SEC_HEADER_X_FRAME_OPTIONS", "SAMEORIGIN");
+        assert_eq!(
+            build_sec_header("SEC_HEADER_X_FRAME_OPTIONS", "DENY"),
+            "SAMEORIGIN"
+        );
+    }
+
+    #[test]
+    fn test_proxy_url_with_auth_socks5_embeds_userinfo() {
+        let url = proxy_url_with_auth(
+            "socks5://proxy.example.com:1080",
+            Some("svc-account"),
+            "s3cr3t",
+        )
+        .unwrap();
+
+        assert_eq!(url.username(), "svc-account");
+        assert_eq!(url.password(), Some("s3cr3t"));
+    }
+
+    #[test]
+    fn test_proxy_url_with_auth_socks5_no_credentials() {
+        let url = proxy_url_with_auth("socks5h://proxy.example.com:1080", None, "").unwrap();
+
+        assert_eq!(url.username(), "");
+        assert_eq!(url.password(), None);
+    }
+
+    #[test]
+    fn test_proxy_url_with_auth_http_leaves_url_untouched() {
+        // basic_auth() is applied separately for HTTP(S) CONNECT proxies, so userinfo must be
+        // left alone here even when credentials are configured.
+        let url = proxy_url_with_auth(
+            "http://proxy.example.com:8080",
+            Some("svc-account"),
+            "s3cr3t",
+        )
+        .unwrap();
+
+        assert_eq!(url.username(), "");
+        assert_eq!(url.password(), None);
+    }
 }